@@ -16,7 +16,7 @@ use sr_primitives::{
 };
 use sr_primitives::traits::{
     NumberFor, Block as BlockT, DigestFor, StaticLookup,
-    BlakeTwo256, Verify,
+    BlakeTwo256, Verify, ConvertInto,
 };
 use sr_primitives::weights::Weight;
 
@@ -26,7 +26,7 @@ use grandpa::fg_primitives::{self, ScheduledChange};
 
 use client::{
     block_builder::api::{CheckInherentsResult, InherentData, self as block_builder_api},
-    runtime_api as client_api, impl_runtime_apis
+    runtime_api as client_api, impl_runtime_apis, decl_runtime_apis,
 };
 use system::IsDeadAccount;
 
@@ -38,12 +38,16 @@ use version::NativeVersion;
 #[cfg(any(feature = "std", test))]
 pub use sr_primitives::BuildStorage;
 pub use timestamp::Call as TimestampCall;
+pub use balances::Call as BalancesCall;
 pub use sr_primitives::{Permill, Perbill};
 pub use support::{StorageValue, construct_runtime, parameter_types};
 
 /// Index of a block number in the chain.
 pub type BlockNumber = u32;
 
+/// Balance of an account.
+pub type Balance = u128;
+
 /// Alias to 512-bit hash when used in the context of a transaction signature on the chain.
 pub type Signature = AnySignature;
 
@@ -65,7 +69,11 @@ pub type Hash = primitives::H256;
 pub type DigestItem = generic::DigestItem<Hash>;
 
 mod plasma_cash;
-pub use plasma_cash::{TokenId, Transaction};
+pub use plasma_cash::{TokenId, BlkNum, Transaction, PlasmaSignature, TransferBlockedReason, Exit, PlasmaCall};
+
+/// Helpers for building plasma transactions off-chain
+#[cfg(feature = "std")]
+pub mod txn_builder;
 
 /// Opaque types. These are used by the CLI to instantiate machinery that don't need to know
 /// the specifics of the runtime. They can then be made to be agnostic over specific formats
@@ -100,8 +108,8 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
     spec_name: create_runtime_str!("plasma-cash"),
     impl_name: create_runtime_str!("plasma-cash"),
     authoring_version: 3,
-    spec_version: 4,
-    impl_version: 4,
+    spec_version: 5,
+    impl_version: 5,
     apis: RUNTIME_API_VERSIONS,
 };
 
@@ -238,9 +246,62 @@ parameter_types! {
     pub const TransactionByteFee: u128 = 1;
 }
 
+impl balances::Trait for Runtime {
+    /// The type for recording an account's balance.
+    type Balance = Balance;
+    /// What to do if an account's free balance gets zeroed.
+    type OnFreeBalanceZero = ();
+    /// What to do if a new account is created.
+    type OnNewAccount = Indices;
+    /// The ubiquitous event type.
+    type Event = Event;
+    type TransactionPayment = ();
+    type DustRemoval = ();
+    type TransferPayment = ();
+    type ExistentialDeposit = ExistentialDeposit;
+    type TransferFee = TransferFee;
+    type CreationFee = CreationFee;
+    type TransactionBaseFee = TransactionBaseFee;
+    type TransactionByteFee = TransactionByteFee;
+    type WeightToFee = ConvertInto;
+}
+
+parameter_types! {
+    pub const MinBlockInterval: BlockNumber = MINUTES;
+    pub const MaxHistoryLength: u32 = 100;
+    pub const DepositFee: Balance = 0;
+    pub const AnchorMaxAge: u32 = 10;
+    pub const MaxExitsPerSweep: u32 = 10;
+    pub const ChallengeBond: Balance = 100;
+    pub const ExitBond: Balance = 100;
+    pub const RootRetentionBlocks: u32 = 10_000;
+    pub const MaxScheduledPerBlock: u32 = 10;
+    pub const MaintenanceItemWeight: Weight = 1_000;
+    pub const GiftTimeout: u32 = 100;
+    pub const TokenBitOrder: plasma_cash::BitOrder = plasma_cash::BitOrder::BigEndian;
+}
+
 impl plasma_cash::Trait for Runtime {
     type Event = Event;
-    type Signature = Signature;
+    type Signature = PlasmaSignature;
+    type MinBlockInterval = MinBlockInterval;
+    type EmptyLeafHash = plasma_cash::DefaultEmptyLeaf;
+    type MaxHistoryLength = MaxHistoryLength;
+    type Currency = Balances;
+    type DepositFee = DepositFee;
+    type FeeDestination = plasma_cash::FeesToOperator<Runtime>;
+    type OnTransfer = ();
+    type AnchorMaxAge = AnchorMaxAge;
+    type ForceOrigin = system::EnsureRoot<AccountId>;
+    type MaxExitsPerSweep = MaxExitsPerSweep;
+    type ChallengeBond = ChallengeBond;
+    type ExitBond = ExitBond;
+    type BridgeOrigin = system::EnsureRoot<AccountId>;
+    type RootRetentionBlocks = RootRetentionBlocks;
+    type MaxScheduledPerBlock = MaxScheduledPerBlock;
+    type MaintenanceItemWeight = MaintenanceItemWeight;
+    type GiftTimeout = GiftTimeout;
+    type TokenBitOrder = TokenBitOrder;
 }
 
 construct_runtime!(
@@ -255,6 +316,8 @@ construct_runtime!(
         Grandpa: grandpa::{Module, Call, Storage, Config, Event},
         Indices: indices::{default, Config<T>},
         PlasmaCash: plasma_cash::{Module, Call, Storage, Event<T>, Config<T>},
+        // Note: Appended, so the modules before it keep their call and event indices
+        Balances: balances::{Module, Call, Storage, Config<T>, Event<T>},
     }
 );
 
@@ -303,6 +366,31 @@ pub type CheckedExtrinsic = generic::CheckedExtrinsic<AccountId, Call, SignedExt
 /// Executive: handles dispatch to the various modules.
 pub type Executive = executive::Executive<Runtime, Block, system::ChainContext<Runtime>, Runtime, AllModules>;
 
+decl_runtime_apis! {
+    /// Plasma Cash queries for off-chain tools
+    pub trait PlasmaCashApi {
+        /// Whether the transaction is signed by its sender
+        fn verify_transaction_signature(txn: Transaction<AccountId, PlasmaSignature>) -> bool;
+        /// Whether an account can send a token to another right now, and why not.
+        /// `proof` is the receiver-policy proof the transfer would carry.
+        fn can_transfer(
+            token_id: TokenId,
+            from: AccountId,
+            to: AccountId,
+            proof: Option<Vec<Hash>>,
+        ) -> Result<(), TransferBlockedReason>;
+        /// Exits in progress with their owner and start and finalizable blocks
+        fn pending_exits() -> Vec<(TokenId, Exit<AccountId>)>;
+        /// Tokens the chain launched with and their first owners
+        fn genesis_tokens() -> Vec<(TokenId, AccountId)>;
+        /// A token's transactions, oldest first, with the plasma block each
+        /// was included in and its root, if still published
+        fn coin_history(token_id: TokenId) -> Vec<(BlkNum, Transaction<AccountId, PlasmaSignature>, Option<Hash>)>;
+        /// `prev_blk_num` the next transfer of a token must have
+        fn next_prev_blk_num(token_id: TokenId) -> Option<BlkNum>;
+    }
+}
+
 // Implement our runtime API endpoints. This is just a bunch of proxying.
 impl_runtime_apis! {
     impl client_api::Core<Block> for Runtime {
@@ -403,6 +491,37 @@ impl_runtime_apis! {
         }
     }
 
+    impl self::PlasmaCashApi<Block> for Runtime {
+        fn verify_transaction_signature(txn: Transaction<AccountId, PlasmaSignature>) -> bool {
+            PlasmaCash::verify_transaction_signature(txn)
+        }
+
+        fn can_transfer(
+            token_id: TokenId,
+            from: AccountId,
+            to: AccountId,
+            proof: Option<Vec<Hash>>,
+        ) -> Result<(), TransferBlockedReason> {
+            PlasmaCash::can_transfer(token_id, from, to, proof)
+        }
+
+        fn pending_exits() -> Vec<(TokenId, Exit<AccountId>)> {
+            PlasmaCash::pending_exits()
+        }
+
+        fn genesis_tokens() -> Vec<(TokenId, AccountId)> {
+            PlasmaCash::genesis_tokens()
+        }
+
+        fn coin_history(token_id: TokenId) -> Vec<(BlkNum, Transaction<AccountId, PlasmaSignature>, Option<Hash>)> {
+            PlasmaCash::coin_history(token_id)
+        }
+
+        fn next_prev_blk_num(token_id: TokenId) -> Option<BlkNum> {
+            PlasmaCash::next_prev_blk_num(token_id)
+        }
+    }
+
     impl substrate_session::SessionKeys<Block> for Runtime {
         fn generate_session_keys(seed: Option<Vec<u8>>) -> Vec<u8> {
             let seed = seed.as_ref().map(|s| rstd::str::from_utf8(&s).expect("Seed is an utf8 string"));
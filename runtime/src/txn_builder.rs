@@ -0,0 +1,136 @@
+///
+/// Author: Zatoichi Labs
+///
+// Off-chain helpers for wallets building plasma transactions
+
+use codec::{Decode, Encode};
+use primitives::{H256, Pair};
+use sr_primitives::traits::{Member, Verify};
+
+use crate::plasma_cash::{BlkNum, TokenId, Transaction, UnsignedTransaction};
+
+/// Builds a signed `Transaction` field by field
+///
+/// ```ignore
+/// let txn = TransactionBuilder::new()
+///     .receiver(bob.public())
+///     .token_id(token_id)
+///     .prev_blk_num(blk_num)
+///     .sign_with(&alice)?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct TransactionBuilder<AccountId> {
+    receiver: Option<AccountId>,
+    token_id: Option<TokenId>,
+    prev_blk_num: Option<BlkNum>,
+    anchor: Option<(BlkNum, H256)>,
+}
+
+impl<AccountId> Default for TransactionBuilder<AccountId> {
+    fn default() -> Self {
+        Self {
+            receiver: None,
+            token_id: None,
+            prev_blk_num: None,
+            anchor: None,
+        }
+    }
+}
+
+impl<AccountId> TransactionBuilder<AccountId>
+    where AccountId: Default + Encode + Decode + Member,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn receiver(mut self, receiver: AccountId) -> Self {
+        self.receiver = Some(receiver);
+        self
+    }
+
+    pub fn token_id(mut self, token_id: TokenId) -> Self {
+        self.token_id = Some(token_id);
+        self
+    }
+
+    /// Plasma block the token's current transaction was included in
+    pub fn prev_blk_num(mut self, prev_blk_num: BlkNum) -> Self {
+        self.prev_blk_num = Some(prev_blk_num);
+        self
+    }
+
+    /// Latest published block and root, see `UnsignedTransaction::with_anchor`
+    pub fn anchor(mut self, blk_num: BlkNum, root: H256) -> Self {
+        self.anchor = Some((blk_num, root));
+        self
+    }
+
+    /// The transaction without a signature, if all fields are set
+    pub fn build_unsigned(self) -> Result<UnsignedTransaction<AccountId>, &'static str> {
+        let unsigned_txn = UnsignedTransaction::new(
+            self.receiver.ok_or("Transaction is missing a receiver!")?,
+            self.token_id.ok_or("Transaction is missing a token id!")?,
+            self.prev_blk_num.ok_or("Transaction is missing a previous block number!")?,
+        );
+        Ok(match self.anchor {
+            Some((blk_num, root)) => unsigned_txn.with_anchor(blk_num, root),
+            None => unsigned_txn,
+        })
+    }
+
+    /// Sign the transaction as the sender, checking the signature verifies
+    pub fn sign_with<P, Signature>(self, pair: &P) -> Result<Transaction<AccountId, Signature>, &'static str>
+        where P: Pair,
+              P::Public: Into<AccountId>,
+              P::Signature: Into<Signature>,
+              Signature: Encode + Decode + Member + Verify<Signer = AccountId>,
+    {
+        let unsigned_txn = self.build_unsigned()?;
+        let signature = pair.sign(unsigned_txn.hash().as_ref());
+        unsigned_txn.add_signature(pair.public().into(), signature.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use primitives::sr25519;
+
+    use crate::plasma_cash::PlasmaSignature;
+
+    type AccountId = sr25519::Public;
+
+    fn create_acct(id: u64) -> sr25519::Pair {
+        sr25519::Pair::from_string(&format!("//{}", id), None)
+            .expect("static values are valid; qed")
+    }
+
+    #[test]
+    fn test_can_build_transaction() {
+        let account1 = create_acct(1);
+        let account2 = create_acct(2);
+        let txn: Transaction<AccountId, PlasmaSignature> = TransactionBuilder::new()
+            .receiver(account2.public())
+            .token_id(TokenId::from(123))
+            .prev_blk_num(BlkNum::from(4))
+            .sign_with(&account1)
+            .unwrap();
+        assert!(txn.valid());
+        assert_eq!(txn.sender, account1.public());
+        assert_eq!(txn.receiver, account2.public());
+        assert_eq!(txn.token_id, TokenId::from(123));
+        assert_eq!(txn.prev_blk_num, BlkNum::from(4));
+    }
+
+    #[test]
+    fn test_cant_build_without_token_id() {
+        let account1 = create_acct(1);
+        let result: Result<Transaction<AccountId, PlasmaSignature>, _> = TransactionBuilder::new()
+            .receiver(account1.public())
+            .prev_blk_num(BlkNum::from(0))
+            .sign_with(&account1);
+        assert_eq!(result, Err("Transaction is missing a token id!"));
+    }
+}
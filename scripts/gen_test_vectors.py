@@ -0,0 +1,89 @@
+#!/usr/bin/env python3
+"""
+Generate the shared transaction test vectors in runtime/test-vectors/.
+
+The runtime tests check themselves against the output of this script, so
+wallet and firmware implementations can check against the same file.
+
+    python3 scripts/gen_test_vectors.py > runtime/test-vectors/transactions.txt
+"""
+from hashlib import blake2b
+
+SIGNING_DOMAIN_V2 = b"plasma-cash:tx:2"
+
+
+def blake2_256(data):
+    return blake2b(data, digest_size=32).digest()
+
+
+def be32(value):
+    return value.to_bytes(32, "big")
+
+
+def le32(value):
+    return value.to_bytes(32, "little")
+
+
+def v1_payload(receiver, token_id, prev_blk_num):
+    # SCALE encoding of (receiver, token_id, prev_blk_num)
+    return receiver + le32(token_id) + le32(prev_blk_num)
+
+
+def v2_payload(receiver, token_id, prev_blk_num, anchor=None):
+    payload = SIGNING_DOMAIN_V2 + receiver + be32(token_id) + be32(prev_blk_num)
+    if anchor is None:
+        return payload + bytes(65)
+    anchor_blk, anchor_root = anchor
+    return payload + b"\x01" + be32(anchor_blk) + anchor_root
+
+
+def signing_vector(name, version, receiver, token_id, prev_blk_num, anchor=None):
+    if version == 1:
+        payload = v1_payload(receiver, token_id, prev_blk_num)
+    else:
+        payload = v2_payload(receiver, token_id, prev_blk_num, anchor)
+    fields = [
+        ("name", name),
+        ("version", str(version)),
+        ("receiver", receiver.hex()),
+        ("token_id", be32(token_id).hex()),
+        ("prev_blk_num", be32(prev_blk_num).hex()),
+    ]
+    if anchor is not None:
+        fields.append(("anchor_blk", be32(anchor[0]).hex()))
+        fields.append(("anchor_root", anchor[1].hex()))
+    fields.append(("payload", payload.hex()))
+    fields.append(("hash", blake2_256(payload).hex()))
+    return fields
+
+
+def plasma_bytes_vector(name, receiver, token_id, prev_blk_num, signature, sender):
+    data = receiver + be32(token_id) + be32(prev_blk_num) + signature + sender
+    return [
+        ("name", name),
+        ("receiver", receiver.hex()),
+        ("token_id", be32(token_id).hex()),
+        ("prev_blk_num", be32(prev_blk_num).hex()),
+        ("signature", signature.hex()),
+        ("sender", sender.hex()),
+        ("plasma_bytes", data.hex()),
+    ]
+
+
+def main():
+    vectors = [
+        signing_vector("v1", 1, b"\xaa" * 32, 0x0102, 7),
+        signing_vector("v2", 2, b"\xaa" * 32, 0x0102, 7),
+        signing_vector("v2-anchored", 2, b"\x01" * 32, 123, 4, (3, b"\x11" * 32)),
+        plasma_bytes_vector("plasma-bytes", b"\x01" * 32, 0x0102, 7, b"\x03" * 64, b"\x02" * 32),
+    ]
+    print("# Transaction test vectors, generated by scripts/gen_test_vectors.py.")
+    print("# Numbers are 32 byte big-endian hex. Do not edit by hand.")
+    for fields in vectors:
+        print()
+        for key, value in fields:
+            print("{} = {}".format(key, value))
+
+
+if __name__ == "__main__":
+    main()
@@ -1,17 +1,22 @@
 // TODO: Consider AnySignature instead of H512
-use primitives::{Pair, Public, U256, sr25519};
+use primitives::{Pair, Public, sr25519, hexdisplay::HexDisplay};
+use sr_io::blake2_256;
 use plasma_cash_runtime::{
-    AccountId, Signature, Transaction, TokenId,
-    BabeConfig, GenesisConfig, GrandpaConfig, SystemConfig, PlasmaCashConfig,
+    AccountId, PlasmaSignature, Transaction, TokenId, BlkNum,
+    BabeConfig, BalancesConfig, GenesisConfig, GrandpaConfig, SystemConfig, PlasmaCashConfig,
     WASM_BINARY,
 };
 use babe_primitives::{AuthorityId as BabeId};
 use grandpa_primitives::{AuthorityId as GrandpaId};
-use substrate_service;
+use substrate_service::{self, Properties};
+use log::warn;
 
 // Note this is the URL for the telemetry server
 //const STAGING_TELEMETRY_URL: &str = "wss://telemetry.polkadot.io/submit/";
 
+/// Chain spec property declaring the `blake2_256` hash of the runtime wasm
+pub const CODE_HASH_PROPERTY: &str = "codeHash";
+
 /// Specialized `ChainSpec`. This is a specialization of the general Substrate ChainSpec type.
 pub type ChainSpec = substrate_service::ChainSpec<GenesisConfig>;
 
@@ -41,14 +46,14 @@ pub fn get_authority_keys_from_seed(seed: &str) -> (AccountId, AccountId, Grandp
     )
 }
 
-fn txn_for_genesis_acct(seed: &str, token_id: TokenId) -> Transaction<AccountId, Signature> {
+pub(crate) fn txn_for_genesis_acct(seed: &str, token_id: TokenId) -> Transaction<AccountId, PlasmaSignature> {
     let owner = sr25519::Pair::from_string(&format!("//{}", seed), None)
         .expect("static values are valid; qed");
     // Construct unsigned transaction
-    let unsigned_txn = Transaction::<AccountId, Signature>::new(
+    let unsigned_txn = Transaction::<AccountId, PlasmaSignature>::new(
         owner.public().clone(),
         token_id,
-        U256::from(0), // Genesis block
+        BlkNum::zero(), // Genesis block
     );
     let signature = owner.sign(unsigned_txn.hash().as_ref());
     unsigned_txn.add_signature(owner.public(), signature.into()).unwrap()
@@ -65,6 +70,7 @@ impl Alternative {
                     vec![ // Authorities
                         get_authority_keys_from_seed("Alice"),
                     ],
+                    get_from_seed::<AccountId>("Alice"), // Operator
                     vec![ // Token Distribution
                         txn_for_genesis_acct("Alice", TokenId::from(1)),
                     ],
@@ -84,6 +90,7 @@ impl Alternative {
                         get_authority_keys_from_seed("Alice"),
                         get_authority_keys_from_seed("Bob"),
                     ],
+                    get_from_seed::<AccountId>("Alice"), // Operator
                     vec![ // Token Distribution
                         txn_for_genesis_acct("Charlie", TokenId::from(1)),
                         txn_for_genesis_acct("Dave",    TokenId::from(2)),
@@ -112,17 +119,60 @@ impl Alternative {
     }
 }
 
-fn testnet_genesis(
+/// Check the runtime wasm against the hash declared in the chain spec properties,
+/// so nodes built from different commits don't fork at block 1. Specs without a
+/// declared hash (e.g. dev and local) are not checked.
+pub fn check_code_hash(
+    properties: &Properties,
+    code: &[u8],
+    allow_mismatch: bool,
+) -> Result<(), String> {
+    let expected = match properties.get(CODE_HASH_PROPERTY) {
+        Some(value) => value.as_str()
+            .ok_or_else(|| format!("`{}` property must be a hex string", CODE_HASH_PROPERTY))?
+            .to_lowercase(),
+        None => return Ok(()),
+    };
+    let actual = format!("0x{}", HexDisplay::from(&blake2_256(code)));
+
+    if expected == actual {
+        Ok(())
+    } else if allow_mismatch {
+        warn!("Runtime code hash {} does not match chain spec ({}), continuing anyway", actual, expected);
+        Ok(())
+    } else {
+        Err(format!(
+            "Runtime code hash {} does not match chain spec ({}). \
+             Rebuild from the commit the spec was generated for, or pass --allow-code-mismatch.",
+            actual,
+            expected,
+        ))
+    }
+}
+
+pub(crate) fn testnet_genesis(
     initial_authorities: Vec<(AccountId, AccountId, GrandpaId, BabeId)>,
-    initial_tokendb: Vec<Transaction<AccountId, Signature>>,
+    operator: AccountId,
+    initial_tokendb: Vec<Transaction<AccountId, PlasmaSignature>>,
     _enable_println: bool
 ) -> GenesisConfig {
+    // The operator submits plasma blocks, so must be producing them
+    assert!(
+        initial_authorities.iter().any(|x| x.1 == operator),
+        "Operator {} is not one of the initial authorities",
+        operator,
+    );
+
     GenesisConfig {
         system: Some(SystemConfig {
             code: WASM_BINARY.to_vec(),
             changes_trie_config: Default::default(),
         }),
         indices: None,
+        balances: Some(BalancesConfig {
+            balances: initial_authorities.iter().map(|x| (x.1.clone(), 1 << 60)).collect(),
+            vesting: vec![],
+        }),
         babe: Some(BabeConfig {
             authorities: initial_authorities.iter().map(|x| (x.3.clone(), 1)).collect(),
         }),
@@ -131,6 +181,70 @@ fn testnet_genesis(
         }),
         plasma_cash: Some(PlasmaCashConfig {
             initial_tokendb, // Initialize SMT
+            operator,
+            reserved_ranges: vec![],
+            legacy_events_enabled: true,
+            challenge_period: BlkNum::from(10),
+            max_mutations_per_plasma_block: 0,
         }),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn properties_with_hash(code: &[u8]) -> Properties {
+        let mut properties = Properties::new();
+        properties.insert(
+            CODE_HASH_PROPERTY.into(),
+            Value::String(format!("0x{}", HexDisplay::from(&blake2_256(code)))),
+        );
+        properties
+    }
+
+    #[test]
+    fn test_code_hash_match() {
+        let properties = properties_with_hash(b"runtime");
+        assert_eq!(check_code_hash(&properties, b"runtime", false), Ok(()));
+    }
+
+    #[test]
+    fn test_code_hash_mismatch() {
+        let properties = properties_with_hash(b"runtime");
+        assert!(check_code_hash(&properties, b"other runtime", false).is_err());
+    }
+
+    #[test]
+    fn test_code_hash_mismatch_allowed() {
+        let properties = properties_with_hash(b"runtime");
+        assert_eq!(check_code_hash(&properties, b"other runtime", true), Ok(()));
+    }
+
+    #[test]
+    #[should_panic(expected = "is not one of the initial authorities")]
+    fn test_operator_must_be_authority() {
+        testnet_genesis(
+            vec![get_authority_keys_from_seed("Bob")],
+            get_from_seed::<AccountId>("Alice"),
+            vec![],
+            false,
+        );
+    }
+
+    #[test]
+    fn test_operator_is_authority() {
+        testnet_genesis(
+            vec![get_authority_keys_from_seed("Alice"), get_authority_keys_from_seed("Bob")],
+            get_from_seed::<AccountId>("Bob"),
+            vec![],
+            false,
+        );
+    }
+
+    #[test]
+    fn test_code_hash_not_declared() {
+        assert_eq!(check_code_hash(&Properties::new(), b"runtime", false), Ok(()));
+    }
+}
@@ -3,10 +3,22 @@ use futures::{future, Future, sync::oneshot};
 use std::cell::RefCell;
 use tokio::runtime::Runtime;
 pub use substrate_cli::{VersionInfo, IntoExit, error};
-use substrate_cli::{informant, parse_and_prepare, ParseAndPrepare, NoCustom};
+use substrate_cli::{informant, parse_and_prepare, ParseAndPrepare, NoCustom, impl_augment_clap};
 use substrate_service::{AbstractService, Roles as ServiceRoles};
+use structopt::StructOpt;
 use crate::chain_spec;
 use log::info;
+use plasma_cash_runtime::WASM_BINARY;
+
+/// Extra flags for running the node.
+#[derive(Debug, Clone, StructOpt)]
+pub struct CustomArgs {
+    /// Start even if the runtime doesn't match the code hash declared by the chain spec.
+    #[structopt(long = "allow-code-mismatch")]
+    allow_code_mismatch: bool,
+}
+
+impl_augment_clap!(CustomArgs);
 
 /// Parse command line arguments into service configuration.
 pub fn run<I, T, E>(args: I, exit: E, version: VersionInfo) -> error::Result<()> where
@@ -14,10 +26,15 @@ pub fn run<I, T, E>(args: I, exit: E, version: VersionInfo) -> error::Result<()>
     T: Into<std::ffi::OsString> + Clone,
     E: IntoExit,
 {
-    match parse_and_prepare::<NoCustom, NoCustom, _>(&version, "substrate-node", args) {
+    match parse_and_prepare::<NoCustom, CustomArgs, _>(&version, "substrate-node", args) {
         ParseAndPrepare::Run(cmd) => cmd.run::<(), _, _, _, _>(load_spec,
                                                                exit,
-                                                               |exit, _cli_args, _custom_args, config| {
+                                                               |exit, _cli_args, custom_args, config| {
+            chain_spec::check_code_hash(
+                &config.chain_spec.properties(),
+                WASM_BINARY,
+                custom_args.allow_code_mismatch,
+            )?;
             info!("{}", version.name);
             info!("  version {}", config.full_version());
             info!("  by {}, 2017, 2018", version.author);
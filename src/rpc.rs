@@ -0,0 +1,298 @@
+//! Plasma Cash RPC methods, served next to substrate's own.
+//!
+//! Each method answers from the best block, through `PlasmaCashApi`.
+
+use std::sync::Arc;
+use jsonrpc_core::{Error as RpcError, ErrorCode, Result};
+use jsonrpc_derive::rpc;
+use serde::{Deserialize, Serialize};
+use sr_primitives::{generic::BlockId, traits::ProvideRuntimeApi};
+use substrate_client::blockchain::HeaderBackend;
+use plasma_cash_runtime::{
+    opaque::Block, AccountId, BlkNum, Exit, Hash, PlasmaCashApi, PlasmaSignature, TokenId, Transaction,
+};
+
+/// Error code of a failed runtime API call
+const RUNTIME_ERROR: i64 = 1;
+
+/// An exit in progress, see `plasma_pendingExits`
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingExit {
+    pub token_id: TokenId,
+    /// Owner the token is exiting to
+    pub owner: AccountId,
+    /// Plasma block the exit was started in
+    pub started: BlkNum,
+    /// Plasma block from which the exit can be finalized
+    pub finalizable: BlkNum,
+}
+
+/// A transaction of a coin's history, see `plasma_getCoinHistory`
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CoinHistoryEntry {
+    /// Plasma block the transaction was included in
+    pub blk_num: BlkNum,
+    pub transaction: Transaction<AccountId, PlasmaSignature>,
+    /// Root of that plasma block, if it's still kept
+    pub root: Option<Hash>,
+}
+
+/// Chain state the RPC methods read
+pub trait PlasmaCashQueries: Send + Sync + 'static {
+    /// See `PlasmaCashApi::pending_exits`
+    fn pending_exits(&self) -> std::result::Result<Vec<(TokenId, Exit<AccountId>)>, String>;
+
+    /// See `PlasmaCashApi::coin_history`
+    fn coin_history(&self, token_id: TokenId)
+        -> std::result::Result<Vec<(BlkNum, Transaction<AccountId, PlasmaSignature>, Option<Hash>)>, String>;
+}
+
+impl<C> PlasmaCashQueries for Arc<C>
+    where C: ProvideRuntimeApi + HeaderBackend<Block> + Send + Sync + 'static,
+          C::Api: PlasmaCashApi<Block>,
+{
+    fn pending_exits(&self) -> std::result::Result<Vec<(TokenId, Exit<AccountId>)>, String> {
+        let at = BlockId::hash(self.info().best_hash);
+        self.runtime_api().pending_exits(&at).map_err(|e| format!("{:?}", e))
+    }
+
+    fn coin_history(&self, token_id: TokenId)
+        -> std::result::Result<Vec<(BlkNum, Transaction<AccountId, PlasmaSignature>, Option<Hash>)>, String>
+    {
+        let at = BlockId::hash(self.info().best_hash);
+        self.runtime_api().coin_history(&at, token_id).map_err(|e| format!("{:?}", e))
+    }
+}
+
+#[rpc]
+pub trait PlasmaCashRpcApi {
+    /// Exits in progress with their owner and start and finalizable blocks,
+    /// e.g. for exit monitoring
+    #[rpc(name = "plasma_pendingExits")]
+    fn pending_exits(&self) -> Result<Vec<PendingExit>>;
+
+    /// A coin's transactions, oldest first, with the plasma block each was
+    /// included in and its root, for wallets to validate the coin against.
+    /// Roots only commit to the tree, so the sibling hashes of each inclusion
+    /// proof come from the operator's block data.
+    #[rpc(name = "plasma_getCoinHistory")]
+    fn coin_history(&self, token_id: TokenId) -> Result<Vec<CoinHistoryEntry>>;
+}
+
+/// `PlasmaCashRpcApi` over some chain state
+pub struct PlasmaCashRpc<Q> {
+    queries: Q,
+}
+
+impl<Q> PlasmaCashRpc<Q> {
+    pub fn new(queries: Q) -> Self {
+        PlasmaCashRpc { queries }
+    }
+}
+
+impl<Q: PlasmaCashQueries> PlasmaCashRpcApi for PlasmaCashRpc<Q> {
+    fn pending_exits(&self) -> Result<Vec<PendingExit>> {
+        let exits = self.queries.pending_exits().map_err(runtime_error)?;
+        Ok(exits.into_iter()
+            .map(|(token_id, exit)| PendingExit {
+                token_id,
+                owner: exit.owner,
+                started: exit.started,
+                finalizable: exit.finalizable,
+            })
+            .collect())
+    }
+
+    fn coin_history(&self, token_id: TokenId) -> Result<Vec<CoinHistoryEntry>> {
+        let history = self.queries.coin_history(token_id).map_err(runtime_error)?;
+        Ok(history.into_iter()
+            .map(|(blk_num, transaction, root)| CoinHistoryEntry { blk_num, transaction, root })
+            .collect())
+    }
+}
+
+fn runtime_error(err: String) -> RpcError {
+    RpcError {
+        code: ErrorCode::ServerError(RUNTIME_ERROR),
+        message: "Unable to query the runtime".into(),
+        data: Some(err.into()),
+    }
+}
+
+/// The node's RPC extensions, answering from `client`
+pub fn create<C, M>(client: Arc<C>) -> jsonrpc_core::IoHandler<M>
+    where Arc<C>: PlasmaCashQueries,
+          M: jsonrpc_core::Metadata + Default,
+{
+    let mut io = jsonrpc_core::IoHandler::default();
+    io.extend_with(PlasmaCashRpcApi::to_delegate(PlasmaCashRpc::new(client)));
+    io
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+    use primitives::{Blake2Hasher, Pair, sr25519, H256};
+    use serde_json::Value;
+    use sr_io::{blake2_256, with_externalities, TestExternalities};
+    use plasma_cash_runtime::{BuildStorage, Origin, PlasmaCash, System, MINUTES};
+    use crate::chain_spec::{get_authority_keys_from_seed, get_from_seed, testnet_genesis, txn_for_genesis_acct};
+
+    // Runtime storage queried in-process, with the module functions the
+    // runtime API proxies to
+    struct TestChain(Mutex<TestExternalities<Blake2Hasher>>);
+
+    impl TestChain {
+        fn new(initial_tokendb: Vec<Transaction<AccountId, PlasmaSignature>>) -> Self {
+            let genesis = testnet_genesis(
+                vec![get_authority_keys_from_seed("Alice"), get_authority_keys_from_seed("Bob")],
+                get_from_seed::<AccountId>("Alice"),
+                initial_tokendb,
+                false,
+            );
+            TestChain(Mutex::new(genesis.build_storage().unwrap().into()))
+        }
+
+        fn execute<R>(&self, f: impl FnOnce() -> R) -> R {
+            with_externalities(&mut *self.0.lock(), f)
+        }
+    }
+
+    impl PlasmaCashQueries for TestChain {
+        fn pending_exits(&self) -> std::result::Result<Vec<(TokenId, Exit<AccountId>)>, String> {
+            Ok(self.execute(PlasmaCash::pending_exits))
+        }
+
+        fn coin_history(&self, token_id: TokenId)
+            -> std::result::Result<Vec<(BlkNum, Transaction<AccountId, PlasmaSignature>, Option<Hash>)>, String>
+        {
+            Ok(self.execute(|| PlasmaCash::coin_history(token_id)))
+        }
+    }
+
+    fn call(chain: TestChain, method: &str, params: Value) -> Value {
+        let io: jsonrpc_core::IoHandler = {
+            let mut io = jsonrpc_core::IoHandler::default();
+            io.extend_with(PlasmaCashRpcApi::to_delegate(PlasmaCashRpc::new(chain)));
+            io
+        };
+        let request = serde_json::json!({"jsonrpc": "2.0", "method": method, "params": params, "id": 1});
+        let response = io.handle_request_sync(&request.to_string()).expect("method is registered; qed");
+        let response: Value = serde_json::from_str(&response).unwrap();
+        assert_eq!(response["error"], Value::Null, "{} failed", method);
+        response["result"].clone()
+    }
+
+    fn keys(seed: &str) -> sr25519::Pair {
+        sr25519::Pair::from_string(&format!("//{}", seed), None)
+            .expect("static values are valid; qed")
+    }
+
+    fn signed_txn(from: &sr25519::Pair,
+                  to: AccountId,
+                  token_id: TokenId,
+                  prev_blk_num: BlkNum,
+    ) -> Transaction<AccountId, PlasmaSignature> {
+        let unsigned_txn = Transaction::<AccountId, PlasmaSignature>::new(to, token_id, prev_blk_num);
+        let signature = from.sign(unsigned_txn.hash().as_ref());
+        unsigned_txn.add_signature(from.public(), signature.into()).unwrap()
+    }
+
+    fn hash_pair(left: &H256, right: &H256) -> H256 {
+        H256::from(blake2_256(&[left.as_bytes(), right.as_bytes()].concat()))
+    }
+
+    // Root of a token tree holding only `txn`, as a wallet computes it with
+    // the runtime's big-endian token paths
+    fn lone_leaf_root(txn: &Transaction<AccountId, PlasmaSignature>, empty_leaf: H256) -> H256 {
+        let mut key = [0u8; 32];
+        txn.token_id.to_big_endian(&mut key);
+        let (mut node, mut empty) = (txn.leaf_hash(), empty_leaf);
+        for depth in 0..256 {
+            node = if (key[31 - depth / 8] >> (depth % 8)) & 1 == 1 {
+                hash_pair(&empty, &node)
+            } else {
+                hash_pair(&node, &empty)
+            };
+            empty = hash_pair(&empty, &empty);
+        }
+        node
+    }
+
+    #[test]
+    fn test_pending_exits_rpc() {
+        let alice = get_from_seed::<AccountId>("Alice");
+        let bob = get_from_seed::<AccountId>("Bob");
+        let chain = TestChain::new(vec![
+            txn_for_genesis_acct("Alice", TokenId::from(1)),
+            txn_for_genesis_acct("Bob", TokenId::from(2)),
+        ]);
+        chain.execute(|| {
+            assert_eq!(PlasmaCash::start_exit(Origin::signed(alice.clone()), TokenId::from(1), None), Ok(()));
+            assert_eq!(PlasmaCash::submit_block(Origin::signed(alice.clone()), H256::zero()), Ok(()));
+            assert_eq!(PlasmaCash::start_exit(Origin::signed(bob.clone()), TokenId::from(2), None), Ok(()));
+        });
+
+        let result = call(chain, "plasma_pendingExits", serde_json::json!([]));
+        for field in &["tokenId", "owner", "started", "finalizable"] {
+            assert!(result[0].get(field).is_some(), "missing {}", field);
+        }
+        let exits: Vec<PendingExit> = serde_json::from_value(result).unwrap();
+        assert_eq!(exits, vec![
+            PendingExit {
+                token_id: TokenId::from(1),
+                owner: alice,
+                started: BlkNum::from(0),
+                finalizable: BlkNum::from(10),
+            },
+            PendingExit {
+                token_id: TokenId::from(2),
+                owner: bob,
+                started: BlkNum::from(1),
+                finalizable: BlkNum::from(11),
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_coin_history_rpc_validates_against_roots() {
+        let token_id = TokenId::from(1);
+        let (alice, bob) = (keys("Alice"), keys("Bob"));
+        let deposit = txn_for_genesis_acct("Alice", token_id);
+        let chain = TestChain::new(vec![deposit.clone()]);
+        let empty_leaf = chain.execute(PlasmaCash::empty_leaf_hash);
+
+        // The operator publishes the deposit's block, then the transfer's
+        let transfer = signed_txn(&alice, bob.public(), token_id, BlkNum::from(0));
+        chain.execute(|| {
+            let root = lone_leaf_root(&deposit, empty_leaf);
+            assert_eq!(PlasmaCash::submit_block(Origin::signed(alice.public()), root), Ok(()));
+            assert_eq!(PlasmaCash::transfer(Origin::signed(alice.public()), transfer.clone()), Ok(()));
+            System::set_block_number(System::block_number() + MINUTES);
+            let root = lone_leaf_root(&transfer, empty_leaf);
+            assert_eq!(PlasmaCash::submit_block(Origin::signed(alice.public()), root), Ok(()));
+        });
+
+        let result = call(chain, "plasma_getCoinHistory", serde_json::json!([token_id]));
+        let history: Vec<CoinHistoryEntry> = serde_json::from_value(result).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1].transaction, transfer);
+
+        // What a receiving wallet checks, using nothing but the response
+        for (idx, entry) in history.iter().enumerate() {
+            assert!(entry.transaction.valid());
+            assert_eq!(entry.transaction.token_id, token_id);
+            assert_eq!(entry.root, Some(lone_leaf_root(&entry.transaction, empty_leaf)));
+            if idx > 0 {
+                let parent = &history[idx - 1];
+                assert_eq!(entry.transaction.prev_blk_num, parent.blk_num);
+                assert_eq!(entry.transaction.sender, parent.transaction.receiver);
+                assert!(entry.blk_num > parent.blk_num);
+            }
+        }
+        assert_eq!(history[1].transaction.receiver, bob.public());
+    }
+}
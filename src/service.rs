@@ -23,6 +23,9 @@ native_executor_instance!(
     plasma_cash_runtime::native_version,
 );
 
+/// RPC methods the node serves on top of substrate's
+pub type RpcExtension = jsonrpc_core::IoHandler<substrate_rpc::Metadata>;
+
 construct_simple_protocol! {
     /// Demo protocol attachment for substrate.
     pub struct NodeProtocol where Block = Block { }
@@ -74,6 +77,9 @@ macro_rules! new_full_start {
                 tasks_to_spawn = Some(vec![Box::new(pruning_task)]);
 
                 Ok(import_queue)
+            })?
+            .with_rpc_extensions(|client, _pool| -> crate::service::RpcExtension {
+                crate::rpc::create(client)
             })?;
 
         (builder, import_setup, inherent_data_providers, tasks_to_spawn)